@@ -4,17 +4,6 @@ use serde_json::{Value, json};
 use std::{fs::File, io::Read, path::PathBuf};
 
 fn read_file_to_b64(source: &PathBuf) -> Result<String, String> {
-    if !source.exists() {
-        return Err(format!("File not found: {}", source.display()));
-    }
-
-    if source.is_dir() {
-        return Err(format!(
-            "Source is a directory and must be a file: {}",
-            source.display()
-        ));
-    }
-
     let mut file: File = match File::open(source) {
         Ok(file) => file,
         Err(e) => {
@@ -41,9 +30,9 @@ fn main() {
         .build()
         .unwrap_or_else(|e| fail_json!(e));
 
-    // Safe to unwrap here, all check were validated in the AnsibleModule::new(0)
-    let src_arg: &str = module.params.get("src").unwrap().value.as_str().unwrap();
-    let path_buf: PathBuf = PathBuf::from(src_arg);
+    let path_buf: PathBuf = module
+        .require_readable_file("src")
+        .unwrap_or_else(|e| fail_json!(e));
 
     match read_file_to_b64(&path_buf) {
         Ok(b64_str) => {
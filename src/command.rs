@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::process::Output;
+
+/// Result of running an external command, returned the way Ansible command modules do
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CommandResult {
+    pub rc: i32,
+    pub stdout: String,
+    pub stdout_lines: Vec<String>,
+    pub stderr: String,
+    pub stderr_lines: Vec<String>,
+    pub cmd: Vec<String>,
+}
+
+impl CommandResult {
+    /// Creates a result splitting `stdout` and `stderr` into lines
+    ///
+    /// # Arguments
+    ///
+    /// * `cmd` - Program and its arguments
+    /// * `rc` - Return code of the command
+    /// * `stdout` - Standard output of the command
+    /// * `stderr` - Standard error of the command
+    pub fn new(cmd: Vec<String>, rc: i32, stdout: String, stderr: String) -> Self {
+        Self {
+            rc,
+            stdout_lines: stdout.lines().map(str::to_string).collect(),
+            stdout,
+            stderr_lines: stderr.lines().map(str::to_string).collect(),
+            stderr,
+            cmd,
+        }
+    }
+
+    /// Creates a result from `std::process::Output`, `rc` is -1 if the command was killed by a signal
+    pub fn from_output(cmd: Vec<String>, output: &Output) -> Self {
+        Self::new(
+            cmd,
+            output.status.code().unwrap_or(-1),
+            String::from_utf8_lossy(&output.stdout).into_owned(),
+            String::from_utf8_lossy(&output.stderr).into_owned(),
+        )
+    }
+
+    /// Adds `rc`, `stdout`, `stdout_lines`, `stderr`, `stderr_lines` and `cmd` keys to the result
+    /// `no_log` values in the command are scrubbed by `exit_json` like anywhere else in the output
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ansible_module::command::CommandResult;
+    /// use std::collections::BTreeMap;
+    ///
+    /// let command = CommandResult::new(vec!["true".to_string()], 0, String::new(), String::new());
+    /// let mut result = BTreeMap::new();
+    /// command.fold_into(&mut result);
+    /// assert_eq!(result["rc"], 0);
+    /// ```
+    pub fn fold_into(self, result: &mut BTreeMap<String, Value>) {
+        if let Ok(Value::Object(fields)) = serde_json::to_value(self) {
+            result.extend(fields);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn check_fold_into() {
+        let command: CommandResult = CommandResult::new(
+            vec!["ls".to_string(), "/missing".to_string()],
+            2,
+            "first\nsecond\n".to_string(),
+            "ls: cannot access '/missing'\n".to_string(),
+        );
+        let mut result: BTreeMap<String, Value> = BTreeMap::new();
+        result.insert("msg".to_string(), json!("non-zero return code"));
+
+        command.fold_into(&mut result);
+
+        assert_eq!(
+            json!(result),
+            json!({
+                "msg": "non-zero return code",
+                "rc": 2,
+                "stdout": "first\nsecond\n",
+                "stdout_lines": ["first", "second"],
+                "stderr": "ls: cannot access '/missing'\n",
+                "stderr_lines": ["ls: cannot access '/missing'"],
+                "cmd": ["ls", "/missing"]
+            })
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_from_output() {
+        let output: Output = std::process::Command::new("echo")
+            .arg("hello")
+            .output()
+            .unwrap();
+
+        let command: CommandResult =
+            CommandResult::from_output(vec!["echo".to_string(), "hello".to_string()], &output);
+
+        assert_eq!(command.rc, 0);
+        assert_eq!(command.stdout_lines, vec!["hello".to_string()]);
+        assert!(command.stderr.is_empty());
+    }
+}
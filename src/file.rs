@@ -0,0 +1,682 @@
+use serde_json::{Value, json};
+#[cfg(any(all(unix, feature = "ownership"), feature = "checksum"))]
+use std::path::Path;
+#[cfg(all(unix, feature = "ownership"))]
+use std::{fs, os::unix::fs::MetadataExt};
+use std::{
+    fs::{File, read},
+    path::PathBuf,
+};
+
+use crate::AnsibleModule;
+use crate::ansible_module::ArgumentValue;
+
+/// Permission bits that can be set with `chmod`, including setuid, setgid and sticky bits
+const MODE_MASK: u32 = 0o7777;
+
+impl AnsibleModule {
+    /// Returns the path from a string argument after checking it is an existing readable file
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the module argument with the path
+    pub fn require_readable_file(&self, key: &str) -> Result<PathBuf, String> {
+        let Some(path) = self.params.get(key).and_then(|arg| arg.value.as_str()) else {
+            return Err(format!("'{key}' must be set to a file path"));
+        };
+        let path: PathBuf = PathBuf::from(path);
+
+        if !path.exists() {
+            return Err(format!("File not found: {}", path.display()));
+        }
+        if path.is_dir() {
+            return Err(format!(
+                "'{key}' is a directory and must be a file: {}",
+                path.display()
+            ));
+        }
+        if let Err(e) = File::open(&path) {
+            return Err(format!("File is not readable: {}: {e}", path.display()));
+        }
+        Ok(path)
+    }
+
+    /// Reads the value of `arg` from a file pointed by `file_arg` (e.g. `password` from
+    /// `password_file`), the value is marked as `no_log`, a trailing newline is stripped
+    /// Does nothing if `file_arg` is not set, fails if both are set
+    ///
+    /// # Arguments
+    ///
+    /// * `arg` - Name of the module argument to set
+    /// * `file_arg` - Name of the module argument with the file path
+    pub fn resolve_file_backed(&mut self, arg: &str, file_arg: &str) -> Result<(), String> {
+        let Some((path, content)) = self.read_file_backed(arg, file_arg)? else {
+            return Ok(());
+        };
+        let content: String = match String::from_utf8(content) {
+            Ok(content) => content,
+            Err(_) => {
+                return Err(format!(
+                    "'{file_arg}' file '{path}' is not valid UTF-8, \
+                    use `resolve_file_backed_bytes` for binary content"
+                ));
+            }
+        };
+        self.params.insert(
+            arg.to_string(),
+            ArgumentValue {
+                value: json!(content.trim_end_matches(['\n', '\r'])),
+                no_log: true,
+            },
+        );
+        Ok(())
+    }
+
+    /// Same as `resolve_file_backed`, but for binary content (e.g. a keytab),
+    /// the value is the base64 encoded file content as is
+    ///
+    /// # Arguments
+    ///
+    /// * `arg` - Name of the module argument to set
+    /// * `file_arg` - Name of the module argument with the file path
+    #[cfg(feature = "base64")]
+    pub fn resolve_file_backed_bytes(&mut self, arg: &str, file_arg: &str) -> Result<(), String> {
+        use base64::prelude::{BASE64_STANDARD, Engine};
+
+        let Some((_, content)) = self.read_file_backed(arg, file_arg)? else {
+            return Ok(());
+        };
+        self.params.insert(
+            arg.to_string(),
+            ArgumentValue {
+                value: json!(BASE64_STANDARD.encode(&content)),
+                no_log: true,
+            },
+        );
+        Ok(())
+    }
+
+    /// Reads the file pointed by `file_arg` for `resolve_file_backed`,
+    /// returns its path and content or `None` if `file_arg` is not set
+    fn read_file_backed(
+        &self,
+        arg: &str,
+        file_arg: &str,
+    ) -> Result<Option<(String, Vec<u8>)>, String> {
+        let Some(file_value) = self.params.get(file_arg) else {
+            return Ok(None);
+        };
+        if self.params.contains_key(arg) {
+            return Err(format!(
+                "parameters are mutually exclusive: {arg}|{file_arg}"
+            ));
+        }
+        let Some(path) = file_value.value.as_str() else {
+            return Err(format!("'{file_arg}' must be set to a file path"));
+        };
+
+        match read(path) {
+            Ok(content) => Ok(Some((path.to_string(), content))),
+            Err(e) => Err(format!(
+                "Could not read '{arg}' from '{file_arg}' file '{path}': {e}"
+            )),
+        }
+    }
+
+    /// Returns paths from a list argument with `~` and environment variables
+    /// (`$VAR`, `${VAR}`) expanded, an unset argument gives no paths
+    ///
+    /// # Arguments
+    ///
+    /// * `key` - Name of the module argument with the list of paths
+    /// * `must_exist` - Fail on the first path that does not exist
+    pub fn resolve_paths(&self, key: &str, must_exist: bool) -> Result<Vec<PathBuf>, String> {
+        let Some(arg) = self.params.get(key) else {
+            return Ok(vec![]);
+        };
+        let Some(entries) = arg.value.as_array() else {
+            return Err(format!("'{key}' must be a list of paths"));
+        };
+
+        let mut paths: Vec<PathBuf> = Vec::with_capacity(entries.len());
+        for entry in entries {
+            let Some(entry) = entry.as_str() else {
+                return Err(format!("'{key}' entry {entry} is not a path"));
+            };
+            let path: PathBuf = expand_path(entry);
+            if must_exist && !path.exists() {
+                return Err(format!(
+                    "'{key}' entry '{entry}' does not exist: {}",
+                    path.display()
+                ));
+            }
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// Checks if writing `new_content` to `path` would change the file by comparing
+    /// sha256 digests, a missing file is always changed
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File to compare with
+    /// * `new_content` - Content that would be written to the file
+    #[cfg(feature = "checksum")]
+    pub fn file_changed(&self, path: &Path, new_content: &[u8]) -> Result<bool, String> {
+        use sha2::{Digest, Sha256};
+
+        let current_content: Vec<u8> = match std::fs::read(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(true),
+            Err(e) => {
+                return Err(format!(
+                    "Could not read '{}' to compute its checksum: {e}",
+                    path.display()
+                ));
+            }
+        };
+        Ok(Sha256::digest(current_content) != Sha256::digest(new_content))
+    }
+
+    /// Parses a file mode the way Ansible `mode` option accepts it
+    ///
+    /// # Arguments
+    ///
+    /// * `input` - Either an integer (already parsed octal, e.g. `0o644`), an octal string
+    ///   (e.g. `"0644"`) or a symbolic mode (e.g. `"u+rwx,g-w"`)
+    /// * `current` - Current mode of a file, symbolic modes are applied relative to it
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use ansible_module::AnsibleModule;
+    /// use serde_json::json;
+    ///
+    /// let module = AnsibleModule::default();
+    /// assert_eq!(module.parse_mode(&json!("0644"), None), Ok(0o644));
+    /// assert_eq!(module.parse_mode(&json!("u+x"), Some(0o644)), Ok(0o744));
+    /// ```
+    pub fn parse_mode(&self, input: &Value, current: Option<u32>) -> Result<u32, String> {
+        match input {
+            Value::Number(mode) => match mode.as_u64() {
+                Some(mode) if mode <= u64::from(MODE_MASK) => Ok(mode as u32),
+                _ => Err(format!("Invalid mode {mode}, must be between 0 and 0o7777")),
+            },
+            Value::String(mode) if !mode.is_empty() && mode.chars().all(|c| c.is_digit(8)) => {
+                match u32::from_str_radix(mode, 8) {
+                    Ok(parsed) if parsed <= MODE_MASK => Ok(parsed),
+                    _ => Err(format!("Invalid mode '{mode}', must be between 0 and 7777")),
+                }
+            }
+            Value::String(mode) => parse_symbolic_mode(mode, current.unwrap_or_default()),
+            _ => Err(format!(
+                "Invalid mode {input}, must be an integer or a string"
+            )),
+        }
+    }
+}
+
+#[cfg(all(unix, feature = "ownership"))]
+impl AnsibleModule {
+    /// Changes owner and/or group of a file only if they differ from the current ones
+    /// In check mode nothing is changed, but the result is the same as if it was
+    ///
+    /// # Arguments
+    ///
+    /// * `path` - File to change
+    /// * `owner` - User name or uid, `None` to keep the current owner
+    /// * `group` - Group name or gid, `None` to keep the current group
+    /// * `changed` - Current changed status of the module, returned as is if nothing changes
+    pub fn set_owner_group_if_different(
+        &self,
+        path: &Path,
+        owner: Option<&str>,
+        group: Option<&str>,
+        changed: bool,
+    ) -> Result<bool, String> {
+        use nix::unistd::{Gid, Group, Uid, User, chown};
+
+        let metadata: fs::Metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => return Err(format!("Could not stat '{}': {e}", path.display())),
+        };
+
+        let uid: Option<Uid> = match owner {
+            Some(owner) => match User::from_name(owner) {
+                Ok(Some(user)) => Some(user.uid),
+                _ => match owner.parse::<u32>() {
+                    Ok(uid) => Some(Uid::from_raw(uid)),
+                    Err(_) => return Err(format!("Could not resolve user '{owner}'")),
+                },
+            },
+            None => None,
+        };
+        let gid: Option<Gid> = match group {
+            Some(group) => match Group::from_name(group) {
+                Ok(Some(group)) => Some(group.gid),
+                _ => match group.parse::<u32>() {
+                    Ok(gid) => Some(Gid::from_raw(gid)),
+                    Err(_) => return Err(format!("Could not resolve group '{group}'")),
+                },
+            },
+            None => None,
+        };
+
+        let uid: Option<Uid> = uid.filter(|uid| uid.as_raw() != metadata.uid());
+        let gid: Option<Gid> = gid.filter(|gid| gid.as_raw() != metadata.gid());
+        if uid.is_none() && gid.is_none() {
+            return Ok(changed);
+        }
+
+        if !self.internal_params.check_mode {
+            if let Err(e) = chown(path, uid, gid) {
+                return Err(format!(
+                    "Could not change owner/group of '{}': {e}",
+                    path.display()
+                ));
+            }
+            self.record_change();
+        }
+        Ok(true)
+    }
+}
+
+/// Expands a leading `~` to the home directory and `$VAR`/`${VAR}` to environment variables,
+/// unset variables are left as is
+fn expand_path(path: &str) -> PathBuf {
+    let mut expanded: String = String::with_capacity(path.len());
+    let mut rest: &str = path;
+    if let Ok(home) = std::env::var("HOME")
+        && (rest == "~" || rest.starts_with("~/"))
+    {
+        expanded.push_str(&home);
+        rest = &rest[1..];
+    }
+
+    while let Some(start) = rest.find('$') {
+        expanded.push_str(&rest[..start]);
+        let after: &str = &rest[start + 1..];
+        let (name, len): (&str, usize) = match after.strip_prefix('{') {
+            Some(braced) => match braced.find('}') {
+                Some(end) => (&braced[..end], end + 2),
+                None => ("", 0),
+            },
+            None => {
+                let end: usize = after
+                    .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                    .unwrap_or(after.len());
+                (&after[..end], end)
+            }
+        };
+        match std::env::var(name) {
+            Ok(value) if !name.is_empty() => expanded.push_str(&value),
+            _ => expanded.push_str(&rest[start..=start + len]),
+        }
+        rest = &after[len..];
+    }
+    expanded.push_str(rest);
+    PathBuf::from(expanded)
+}
+
+/// Applies a symbolic mode (e.g. `u+rwx,g=rx,o-w`) to the current mode, see `man chmod`
+fn parse_symbolic_mode(mode: &str, current: u32) -> Result<u32, String> {
+    let mut mode_bits: u32 = current & MODE_MASK;
+    for clause in mode.split(',') {
+        let who_end: usize = clause
+            .find(|c: char| !"ugoa".contains(c))
+            .unwrap_or(clause.len());
+        let (who, mut actions): (&str, &str) = clause.split_at(who_end);
+        if actions.is_empty() {
+            return Err(format!(
+                "Invalid symbolic mode '{mode}', clause '{clause}' has no operator"
+            ));
+        }
+
+        let who_mask: u32 = if who.is_empty() || who.contains('a') {
+            MODE_MASK
+        } else {
+            who.chars().fold(0, |mask, c| {
+                mask | match c {
+                    'u' => 0o4700,
+                    'g' => 0o2070,
+                    _ => 0o1007,
+                }
+            })
+        };
+
+        while let Some(operator) = actions.chars().next() {
+            if !"+-=".contains(operator) {
+                return Err(format!(
+                    "Invalid symbolic mode '{mode}', unexpected '{operator}' in '{clause}'"
+                ));
+            }
+            let rest: &str = &actions[1..];
+            let perms_end: usize = rest.find(|c: char| "+-=".contains(c)).unwrap_or(rest.len());
+            let (perms, remaining): (&str, &str) = rest.split_at(perms_end);
+
+            let mut bits: u32 = 0;
+            for perm in perms.chars() {
+                bits |= match perm {
+                    'r' => 0o444,
+                    'w' => 0o222,
+                    'x' => 0o111,
+                    // Execute only if it is already executable by someone
+                    'X' if mode_bits & 0o111 != 0 => 0o111,
+                    'X' => 0,
+                    's' => 0o6000,
+                    't' => 0o1000,
+                    _ => {
+                        return Err(format!(
+                            "Invalid symbolic mode '{mode}', unknown permission '{perm}'"
+                        ));
+                    }
+                };
+            }
+            bits &= who_mask;
+
+            mode_bits = match operator {
+                '+' => mode_bits | bits,
+                '-' => mode_bits & !bits,
+                _ => (mode_bits & !who_mask) | bits,
+            };
+            actions = remaining;
+        }
+    }
+    Ok(mode_bits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    #[test]
+    fn check_resolve_file_backed() {
+        let mut file: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        writeln!(file, "hunter22").unwrap();
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "password_file".to_string(),
+            ArgumentValue {
+                value: json!(file.path().to_str().unwrap()),
+                no_log: false,
+            },
+        );
+
+        module
+            .resolve_file_backed("password", "password_file")
+            .unwrap();
+
+        let password: &ArgumentValue = module.params.get("password").unwrap();
+        assert_eq!(password.value, json!("hunter22"));
+        assert!(password.no_log);
+    }
+
+    #[test]
+    fn check_resolve_file_backed_both_set() {
+        let file: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "password".to_string(),
+            ArgumentValue {
+                value: json!("hunter22"),
+                no_log: false,
+            },
+        );
+        module.params.insert(
+            "password_file".to_string(),
+            ArgumentValue {
+                value: json!(file.path().to_str().unwrap()),
+                no_log: false,
+            },
+        );
+
+        assert_eq!(
+            module.resolve_file_backed("password", "password_file"),
+            Err("parameters are mutually exclusive: password|password_file".to_string())
+        );
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn check_file_changed() {
+        let mut file: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        write!(file, "listen 80;").unwrap();
+        let module: AnsibleModule = AnsibleModule::default();
+
+        assert_eq!(module.file_changed(file.path(), b"listen 80;"), Ok(false));
+        assert_eq!(module.file_changed(file.path(), b"listen 443;"), Ok(true));
+    }
+
+    #[cfg(feature = "checksum")]
+    #[test]
+    fn check_file_changed_missing() {
+        let dir: tempfile::TempDir = tempfile::tempdir().unwrap();
+        let module: AnsibleModule = AnsibleModule::default();
+
+        assert_eq!(
+            module.file_changed(&dir.path().join("nginx.conf"), b"listen 80;"),
+            Ok(true)
+        );
+    }
+
+    #[test]
+    fn check_require_readable_file() {
+        let file: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "src".to_string(),
+            ArgumentValue {
+                value: json!(file.path().to_str().unwrap()),
+                no_log: false,
+            },
+        );
+
+        assert_eq!(
+            module.require_readable_file("src"),
+            Ok(file.path().to_path_buf())
+        );
+    }
+
+    #[test]
+    fn check_require_readable_file_missing() {
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "src".to_string(),
+            ArgumentValue {
+                value: json!("/nonexistent/file"),
+                no_log: false,
+            },
+        );
+
+        assert_eq!(
+            module.require_readable_file("src"),
+            Err("File not found: /nonexistent/file".to_string())
+        );
+    }
+
+    #[test]
+    fn check_require_readable_file_directory() {
+        let dir: tempfile::TempDir = tempfile::tempdir().unwrap();
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "src".to_string(),
+            ArgumentValue {
+                value: json!(dir.path().to_str().unwrap()),
+                no_log: false,
+            },
+        );
+
+        assert_eq!(
+            module.require_readable_file("src"),
+            Err(format!(
+                "'src' is a directory and must be a file: {}",
+                dir.path().display()
+            ))
+        );
+    }
+
+    #[cfg(all(unix, feature = "ownership"))]
+    #[test]
+    fn check_set_owner_group_if_different_noop() {
+        use nix::unistd::{Group, User, getegid, geteuid};
+        use tempfile::NamedTempFile;
+
+        let module: AnsibleModule = AnsibleModule::default();
+        let file: NamedTempFile = NamedTempFile::new().unwrap();
+        let owner: String = User::from_uid(geteuid()).unwrap().unwrap().name;
+        let group: String = Group::from_gid(getegid()).unwrap().unwrap().name;
+
+        let changed: bool = module
+            .set_owner_group_if_different(file.path(), Some(&owner), Some(&group), false)
+            .unwrap();
+
+        assert!(!changed);
+    }
+
+    #[cfg(all(unix, feature = "ownership"))]
+    #[test]
+    fn check_set_owner_group_if_different_check_mode() {
+        use nix::unistd::geteuid;
+        use std::os::unix::fs::MetadataExt;
+        use tempfile::NamedTempFile;
+
+        // Changing the owner for real needs root, so check mode is used to avoid it
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.internal_params.check_mode = true;
+        let file: NamedTempFile = NamedTempFile::new().unwrap();
+        let other_owner: &str = if geteuid().is_root() {
+            "nobody"
+        } else {
+            "root"
+        };
+
+        let changed: bool = module
+            .set_owner_group_if_different(file.path(), Some(other_owner), None, false)
+            .unwrap();
+
+        assert!(changed);
+        assert_eq!(file.path().metadata().unwrap().uid(), geteuid().as_raw());
+    }
+
+    #[test]
+    fn check_parse_mode_octal_int() {
+        let module: AnsibleModule = AnsibleModule::default();
+
+        assert_eq!(module.parse_mode(&json!(0o644), None), Ok(0o644));
+    }
+
+    #[test]
+    fn check_parse_mode_octal_string() {
+        let module: AnsibleModule = AnsibleModule::default();
+
+        assert_eq!(module.parse_mode(&json!("0644"), None), Ok(0o644));
+        assert_eq!(module.parse_mode(&json!("755"), None), Ok(0o755));
+    }
+
+    #[test]
+    fn check_parse_mode_symbolic() {
+        let module: AnsibleModule = AnsibleModule::default();
+
+        assert_eq!(module.parse_mode(&json!("u+x"), Some(0o644)), Ok(0o744));
+        assert_eq!(
+            module.parse_mode(&json!("u=rw,go=r"), Some(0o777)),
+            Ok(0o644)
+        );
+        assert!(module.parse_mode(&json!("u+q"), Some(0o644)).is_err());
+    }
+
+    #[test]
+    fn check_resolve_paths() {
+        let home: String = std::env::var("HOME").unwrap();
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "paths".to_string(),
+            ArgumentValue {
+                value: json!(["~/.bashrc", "~", "/etc/$HOME", "${HOME}/x", "$UNSET_VAR_/y"]),
+                no_log: false,
+            },
+        );
+
+        assert_eq!(
+            module.resolve_paths("paths", false).unwrap(),
+            vec![
+                PathBuf::from(format!("{home}/.bashrc")),
+                PathBuf::from(&home),
+                PathBuf::from(format!("/etc/{home}")),
+                PathBuf::from(format!("{home}/x")),
+                PathBuf::from("$UNSET_VAR_/y"),
+            ]
+        );
+        assert!(module.resolve_paths("missing", true).unwrap().is_empty());
+    }
+
+    #[test]
+    fn check_resolve_paths_must_exist() {
+        let dir: tempfile::TempDir = tempfile::tempdir().unwrap();
+        let existing: String = dir.path().display().to_string();
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "paths".to_string(),
+            ArgumentValue {
+                value: json!([existing, "/nonexistent/a", "/nonexistent/b"]),
+                no_log: false,
+            },
+        );
+
+        assert_eq!(module.resolve_paths("paths", false).unwrap().len(), 3);
+        assert_eq!(
+            module.resolve_paths("paths", true).unwrap_err(),
+            "'paths' entry '/nonexistent/a' does not exist: /nonexistent/a"
+        );
+    }
+
+    #[test]
+    fn check_resolve_file_backed_not_utf8() {
+        let mut file: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0xde, 0xad, 0xbe, 0xef]).unwrap();
+        let path: &str = file.path().to_str().unwrap();
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "keytab_file".to_string(),
+            ArgumentValue {
+                value: json!(path),
+                no_log: false,
+            },
+        );
+
+        assert_eq!(
+            module
+                .resolve_file_backed("keytab", "keytab_file")
+                .unwrap_err(),
+            format!(
+                "'keytab_file' file '{path}' is not valid UTF-8, \
+                use `resolve_file_backed_bytes` for binary content"
+            )
+        );
+    }
+
+    #[cfg(feature = "base64")]
+    #[test]
+    fn check_resolve_file_backed_bytes() {
+        let mut file: tempfile::NamedTempFile = tempfile::NamedTempFile::new().unwrap();
+        file.write_all(&[0xde, 0xad, 0xbe, 0xef, 0x00]).unwrap();
+        let mut module: AnsibleModule = AnsibleModule::default();
+        module.params.insert(
+            "keytab_file".to_string(),
+            ArgumentValue {
+                value: json!(file.path().to_str().unwrap()),
+                no_log: false,
+            },
+        );
+
+        module
+            .resolve_file_backed_bytes("keytab", "keytab_file")
+            .unwrap();
+
+        let keytab: &ArgumentValue = module.params.get("keytab").unwrap();
+        assert_eq!(keytab.value, json!("3q2+7wA="));
+        assert!(keytab.no_log);
+    }
+}
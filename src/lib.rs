@@ -1,5 +1,7 @@
 pub mod ansible_module;
 pub mod builder;
+pub mod command;
+pub mod file;
 pub mod macros;
 
 pub use ansible_module::AnsibleModule;
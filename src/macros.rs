@@ -70,19 +70,103 @@ macro_rules! exit_json {
 /// fail_json!("Something went horribly (or not) wrong!".to_string());
 /// ```
 ///
+/// Any error that implements `Display` can be passed, e.g. `BuildError`
+///
+/// A module that did part of its work reports it with `changed` and result keys,
+/// see `AnsibleModule::fail_json_partial`
+///
+/// ```
+/// use ansible_module::{AnsibleModule, AnsibleModuleBuilder, fail_json};
+/// use serde_json::json;
+///
+/// let module = AnsibleModuleBuilder::new(json!({}), None)
+///     .build()
+///     .unwrap_or_else(|e| fail_json!(e));
+///
+/// fail_json!(module, true, "Failed to restart nginx".to_string(), "installed" => json!(["nginx"]));
+/// ```
+///
 #[macro_export]
 macro_rules! fail_json {
+    ($self:expr, $changed:expr, $msg:expr $(, $k:literal => $v:expr)*) => {
+        let mut m = ::std::collections::BTreeMap::new();
+        $(
+            m.insert($k.to_string(), $v);
+        )*
+        $self.fail_json_partial($msg, &m, $changed)
+    };
     ($msg: expr) => {
-        AnsibleModule::fail_json($msg)
+        AnsibleModule::fail_json($msg.to_string())
     };
     () => {
         AnsibleModule::fail_json("".to_string())
     };
 }
 
+/// Macros for a less verbose way to write an argument spec
+///
+/// Each argument is `name: type` followed by options: `required` and `no_log` flags
+/// or any other spec key with a single token value (e.g. `default 420`,
+/// `choices ["present", "absent"]`, `bits 8`), negative numbers must be in parentheses
+///
+/// # Examples
+///
+/// ```
+/// use ansible_module::arg_spec;
+/// use serde_json::{json, Value};
+///
+/// let arg_spec: Value = arg_spec! {
+///     src: str required,
+///     mode: int default 420,
+///     state: str choices ["present", "absent"] default "present",
+/// };
+///
+/// assert_eq!(arg_spec["src"], json!({"type": "str", "required": true}));
+/// ```
+///
+#[macro_export]
+macro_rules! arg_spec {
+    (@opts $arg:ident; required $($rest:tt)*) => {
+        $arg.insert("required".to_string(), ::serde_json::Value::Bool(true));
+        $crate::arg_spec!(@opts $arg; $($rest)*);
+    };
+    (@opts $arg:ident; no_log $($rest:tt)*) => {
+        $arg.insert("no_log".to_string(), ::serde_json::Value::Bool(true));
+        $crate::arg_spec!(@opts $arg; $($rest)*);
+    };
+    (@opts $arg:ident; $key:ident $val:tt $($rest:tt)*) => {
+        $arg.insert(stringify!($key).to_string(), ::serde_json::json!($val));
+        $crate::arg_spec!(@opts $arg; $($rest)*);
+    };
+    (@opts $arg:ident;) => {};
+    // Collects option tokens of an argument until the next comma
+    (@arg $spec:ident; $name:ident $ty:ident [$($opts:tt)*] , $($rest:tt)*) => {
+        $crate::arg_spec!(@arg $spec; $name $ty [$($opts)*]);
+        $crate::arg_spec!(@args $spec; $($rest)*);
+    };
+    (@arg $spec:ident; $name:ident $ty:ident [$($opts:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::arg_spec!(@arg $spec; $name $ty [$($opts)* $next] $($rest)*);
+    };
+    (@arg $spec:ident; $name:ident $ty:ident [$($opts:tt)*]) => {
+        let mut arg = ::serde_json::Map::new();
+        arg.insert("type".to_string(), ::serde_json::json!(stringify!($ty)));
+        $crate::arg_spec!(@opts arg; $($opts)*);
+        $spec.insert(stringify!($name).to_string(), ::serde_json::Value::Object(arg));
+    };
+    (@args $spec:ident; $name:ident : $ty:ident $($rest:tt)*) => {
+        $crate::arg_spec!(@arg $spec; $name $ty [] $($rest)*);
+    };
+    (@args $spec:ident;) => {};
+    ($($args:tt)*) => {{
+        let mut spec = ::serde_json::Map::new();
+        $crate::arg_spec!(@args spec; $($args)*);
+        ::serde_json::Value::Object(spec)
+    }};
+}
+
 #[cfg(test)]
 mod tests {
-    use crate::{AnsibleModule, AnsibleModuleBuilder, exit_json};
+    use crate::{AnsibleModule, AnsibleModuleBuilder};
     use serde_json::{Value, json};
     use std::io::Write;
     use std::vec;
@@ -123,4 +207,77 @@ mod tests {
     fn check_fail_json_macro() {
         fail_json!("Something went horribly wrong!".to_string());
     }
+
+    #[test]
+    #[should_panic(
+        expected = r#"{"msg":"Failed to restart nginx","changed":true,"failed":true,"installed":["nginx"],"token":"VALUE_SPECIFIED_IN_NO_LOG_PARAMETER"}"#
+    )]
+    fn check_fail_json_macro_partial() {
+        let arg_spec: Value = json!({
+            "token": {"type": "str", "no_log": true},
+        });
+        let mut file: NamedTempFile = NamedTempFile::new().unwrap();
+        writeln!(file, r#"{{"token": "s3cr3t"}}"#).unwrap();
+        let input_args: Vec<String> = vec![
+            "module_name".to_string(),
+            file.path().to_str().unwrap().to_string(),
+        ];
+
+        let module: AnsibleModule = AnsibleModuleBuilder::new(arg_spec, Some(input_args))
+            .build()
+            .unwrap();
+
+        fail_json!(
+            module,
+            true,
+            "Failed to restart nginx".to_string(),
+            "installed" => json!(["nginx"]),
+            "token" => json!("s3cr3t")
+        );
+    }
+
+    #[test]
+    fn check_arg_spec_macro() {
+        let arg_spec: Value = arg_spec! {
+            src: str required,
+            mode: int default 420,
+            offset: int default (-1),
+            state: str choices ["present", "absent"] default "present",
+            token: str no_log required,
+        };
+
+        assert_eq!(
+            arg_spec,
+            json!({
+                "src": {"type": "str", "required": true},
+                "mode": {"type": "int", "default": 420},
+                "offset": {"type": "int", "default": -1},
+                "state": {"type": "str", "choices": ["present", "absent"], "default": "present"},
+                "token": {"type": "str", "no_log": true, "required": true},
+            })
+        );
+    }
+
+    #[test]
+    fn check_arg_spec_macro_build() {
+        let arg_spec: Value = arg_spec! {
+            src: str required,
+            mode: int default 420,
+            state: str choices ["present", "absent"]
+        };
+        let mut file: NamedTempFile = NamedTempFile::new().unwrap();
+        writeln!(file, r#"{{"src": "/etc/hosts", "state": "absent"}}"#).unwrap();
+        let input_args: Vec<String> = vec![
+            "module_name".to_string(),
+            file.path().to_str().unwrap().to_string(),
+        ];
+
+        let module: AnsibleModule = AnsibleModuleBuilder::new(arg_spec, Some(input_args))
+            .build()
+            .unwrap();
+
+        assert_eq!("/etc/hosts", module.params.get("src").unwrap().value);
+        assert_eq!(420, module.params.get("mode").unwrap().value);
+        assert_eq!("absent", module.params.get("state").unwrap().value);
+    }
 }